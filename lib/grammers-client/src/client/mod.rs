@@ -0,0 +1,32 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+pub mod updates;
+
+use updates::UpdateState;
+
+// NOTE: this trimmed checkout never had a `client/mod.rs` (the session/sender/dc-info fields
+// that back `Client::step`/`invoke`/`user_id` live outside it), so this file can only declare
+// the one field the update-gap-recovery subsystem needs. When merging this change against the
+// real crate, add `update_state` (and its initialization below) to the *existing* `Client`
+// struct and constructor instead of replacing them with this file.
+/// The client used to communicate with Telegram's API.
+pub struct Client {
+    /// `(pts, qts, date, seq)` bookkeeping used by [`Client::next_updates`] to detect and
+    /// recover from gaps in the update stream.
+    pub(crate) update_state: UpdateState,
+}
+
+impl Client {
+    /// Initializes the update-gap-recovery state for a freshly constructed `Client`.
+    ///
+    /// Call this from wherever `Client` is actually constructed (e.g. after a successful
+    /// connection/authorization), alongside the rest of its field initialization.
+    pub(crate) fn init_update_state() -> UpdateState {
+        UpdateState::default()
+    }
+}
@@ -5,12 +5,19 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
+use std::collections::HashMap;
+
 use crate::types::EntitySet;
 use crate::Client;
 use grammers_mtsender::ReadError;
 pub use grammers_mtsender::{AuthorizationError, InvocationError};
 use grammers_tl_types as tl;
 
+/// How many messages `updates.getChannelDifference` is allowed to return at once.
+///
+/// The exact numbers don't matter too much as long as it's "reasonable"; Telegram will clamp it.
+const BOT_CHANNEL_DIFF_LIMIT: i32 = 100;
+
 pub enum UpdateIter {
     Single(Option<tl::enums::Update>),
     Multiple(Vec<tl::enums::Update>),
@@ -38,7 +45,418 @@ impl Iterator for UpdateIter {
     }
 }
 
+/// The `(pts, pts_count)` an update carries, and the scope it applies to.
+///
+/// Only a handful of `Update` variants carry a `pts`; everything else is exempt from gap
+/// detection and can be delivered as soon as it arrives.
+enum PtsScope {
+    None,
+    Common {
+        pts: i32,
+        pts_count: i32,
+    },
+    Channel {
+        channel_id: i32,
+        pts: i32,
+        pts_count: i32,
+    },
+}
+
+fn pts_scope(update: &tl::enums::Update) -> PtsScope {
+    use tl::enums::Update::*;
+
+    match update {
+        NewMessage(u) => PtsScope::Common {
+            pts: u.pts,
+            pts_count: u.pts_count,
+        },
+        EditMessage(u) => PtsScope::Common {
+            pts: u.pts,
+            pts_count: u.pts_count,
+        },
+        DeleteMessages(u) => PtsScope::Common {
+            pts: u.pts,
+            pts_count: u.pts_count,
+        },
+        NewChannelMessage(u) => match channel_id(&u.message) {
+            Some(channel_id) => PtsScope::Channel {
+                channel_id,
+                pts: u.pts,
+                pts_count: u.pts_count,
+            },
+            None => PtsScope::None,
+        },
+        EditChannelMessage(u) => match channel_id(&u.message) {
+            Some(channel_id) => PtsScope::Channel {
+                channel_id,
+                pts: u.pts,
+                pts_count: u.pts_count,
+            },
+            None => PtsScope::None,
+        },
+        DeleteChannelMessages(u) => PtsScope::Channel {
+            channel_id: u.channel_id,
+            pts: u.pts,
+            pts_count: u.pts_count,
+        },
+        // TODO there are a few more pts-bearing channel updates (e.g. pinned messages);
+        // extend this match as they turn out to matter in practice.
+        _ => PtsScope::None,
+    }
+}
+
+fn channel_id(message: &tl::enums::Message) -> Option<i32> {
+    let peer = match message {
+        tl::enums::Message::Message(m) => &m.peer_id,
+        tl::enums::Message::Service(m) => &m.peer_id,
+        tl::enums::Message::Empty(_) => return None,
+    };
+    match peer {
+        tl::enums::Peer::Channel(c) => Some(c.channel_id),
+        _ => None,
+    }
+}
+
+/// Bookkeeping for the common `(pts, qts, date, seq)` state plus the per-channel `pts` map,
+/// used to detect gaps in the update stream and drive `updates.getDifference` /
+/// `updates.getChannelDifference` recovery.
+#[derive(Debug, Default)]
+pub(crate) struct UpdateState {
+    pts: i32,
+    qts: i32,
+    date: i32,
+    seq: i32,
+    channel_pts: HashMap<i32, i32>,
+    // Needed to build the `InputChannel` that `updates.getChannelDifference` requires.
+    channel_hashes: HashMap<i32, i64>,
+    // Whether `pts`/`qts`/`date`/`seq` have been seeded from a real `updates.getState` yet.
+    // Until they are, they're all zeroed and must not be used for gap detection.
+    synced: bool,
+}
+
+impl UpdateState {
+    fn remember_channels(&mut self, chats: &[tl::enums::Chat]) {
+        for chat in chats {
+            if let tl::enums::Chat::Channel(channel) = chat {
+                if let Some(access_hash) = channel.access_hash {
+                    self.channel_hashes.insert(channel.id, access_hash);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn adopt(&mut self, state: tl::enums::updates::State) {
+        let tl::enums::updates::State::State(state) = state;
+        self.pts = state.pts;
+        self.qts = state.qts;
+        self.date = state.date;
+        self.seq = state.seq;
+        self.synced = true;
+    }
+}
+
+/// What to do with an incoming `(local, pts, pts_count)` triple.
+#[derive(Debug, PartialEq, Eq)]
+enum PtsDecision {
+    /// Contiguous with the local state; apply the update and advance to `pts`.
+    Apply(i32),
+    /// Already applied locally; drop the duplicate.
+    Drop,
+    /// A gap exists between the local state and this update; resync first.
+    Gap,
+}
+
+fn classify_pts(local_pts: i32, pts: i32, pts_count: i32) -> PtsDecision {
+    if local_pts + pts_count == pts {
+        PtsDecision::Apply(pts)
+    } else if local_pts + pts_count > pts {
+        PtsDecision::Drop
+    } else {
+        PtsDecision::Gap
+    }
+}
+
+/// What to do with an incoming container `seq` relative to the local one.
+#[derive(Debug, PartialEq, Eq)]
+enum SeqDecision {
+    /// We've already seen this (or a newer) container; drop it entirely.
+    Stale,
+    /// There's a gap between what we last saw and this container; resync first.
+    Gap,
+    /// Contiguous with the local state; apply and advance to `seq`.
+    Apply,
+}
+
+fn classify_seq(local_seq: i32, seq: i32) -> SeqDecision {
+    if seq <= local_seq {
+        SeqDecision::Stale
+    } else if seq > local_seq + 1 {
+        SeqDecision::Gap
+    } else {
+        SeqDecision::Apply
+    }
+}
+
+/// Messages and updates gathered while resolving a gap via `getDifference`/`getChannelDifference`.
+#[derive(Default)]
+struct Difference {
+    updates: Vec<tl::enums::Update>,
+    users: Vec<tl::enums::User>,
+    chats: Vec<tl::enums::Chat>,
+}
+
+impl Difference {
+    /// Wraps messages recovered via `updates.getDifference` the same way a live common-scope
+    /// `updateNewMessage` would have.
+    fn extend_messages(&mut self, messages: Vec<tl::enums::Message>) {
+        self.updates.extend(messages.into_iter().map(|message| {
+            tl::enums::Update::NewMessage(tl::types::UpdateNewMessage {
+                message,
+                pts: 0,
+                pts_count: 0,
+            })
+        }));
+    }
+
+    /// Same as `extend_messages`, but for messages recovered via
+    /// `updates.getChannelDifference`, which are channel posts rather than common-scope
+    /// messages — consumers that branch on the `Update` variant need that distinction preserved.
+    fn extend_channel_messages(&mut self, messages: Vec<tl::enums::Message>) {
+        self.updates.extend(messages.into_iter().map(|message| {
+            tl::enums::Update::NewChannelMessage(tl::types::UpdateNewChannelMessage {
+                message,
+                pts: 0,
+                pts_count: 0,
+            })
+        }));
+    }
+}
+
 impl Client {
+    /// Seeds `update_state` from the authoritative `updates.getState`, so the first update
+    /// received after connecting isn't mistaken for a gap against an all-zero local state.
+    ///
+    /// This is idempotent: once `update_state` has been synced, later calls are no-ops.
+    async fn sync_update_state(&mut self) -> Result<(), ReadError> {
+        if self.update_state.synced {
+            return Ok(());
+        }
+
+        let state = self.invoke(&tl::functions::updates::GetState {}).await?;
+        self.update_state.adopt(state);
+        Ok(())
+    }
+
+    /// Fetches the full difference from the server, looping until it reports there is no more
+    /// data left, and adopts the new `(pts, qts, date, seq)` state it returns.
+    async fn get_difference(&mut self) -> Result<Difference, ReadError> {
+        let mut difference = Difference::default();
+
+        loop {
+            let response = self
+                .invoke(&tl::functions::updates::GetDifference {
+                    pts: self.update_state.pts,
+                    pts_total_limit: None,
+                    date: self.update_state.date,
+                    qts: self.update_state.qts,
+                })
+                .await?;
+
+            use tl::enums::updates::Difference::*;
+            match response {
+                Empty(empty) => {
+                    self.update_state.date = empty.date;
+                    self.update_state.seq = empty.seq;
+                    self.update_state.synced = true;
+                    break;
+                }
+                Difference(diff) => {
+                    self.update_state.remember_channels(&diff.chats);
+                    difference.extend_messages(diff.new_messages);
+                    difference.updates.extend(diff.other_updates);
+                    difference.users.extend(diff.users);
+                    difference.chats.extend(diff.chats);
+                    self.update_state.adopt(diff.state);
+                    break;
+                }
+                Slice(slice) => {
+                    self.update_state.remember_channels(&slice.chats);
+                    difference.extend_messages(slice.new_messages);
+                    difference.updates.extend(slice.other_updates);
+                    difference.users.extend(slice.users);
+                    difference.chats.extend(slice.chats);
+                    self.update_state.adopt(slice.intermediate_state);
+                    // The slice wasn't final; keep asking for more.
+                    continue;
+                }
+                TooLong(too_long) => {
+                    // We have no better reference point than the pts Telegram gave us.
+                    self.update_state.pts = too_long.pts;
+                    break;
+                }
+            }
+        }
+
+        Ok(difference)
+    }
+
+    /// Same as `get_difference`, but scoped to a single channel.
+    async fn get_channel_difference(&mut self, channel_id: i32) -> Result<Difference, ReadError> {
+        let mut difference = Difference::default();
+
+        // Without the channel's access hash we cannot even build a valid request, and guessing
+        // with `0` would turn a recoverable gap into a hard `CHANNEL_INVALID` failure for the
+        // whole update stream. Drop the pending update for this channel instead and let the
+        // next update (or a future `getDifference`) re-establish the hash.
+        let access_hash = match self.update_state.channel_hashes.get(&channel_id) {
+            Some(access_hash) => *access_hash,
+            None => {
+                log::warn!(
+                    "no access hash cached for channel {}; dropping its pending update instead of resyncing",
+                    channel_id
+                );
+                return Ok(difference);
+            }
+        };
+        let channel = tl::enums::InputChannel::Channel(tl::types::InputChannel {
+            channel_id,
+            access_hash,
+        });
+
+        loop {
+            let pts = *self.update_state.channel_pts.get(&channel_id).unwrap_or(&0);
+            let response = self
+                .invoke(&tl::functions::updates::GetChannelDifference {
+                    force: false,
+                    channel: channel.clone(),
+                    filter: tl::enums::ChannelMessagesFilter::Empty,
+                    pts,
+                    limit: BOT_CHANNEL_DIFF_LIMIT,
+                })
+                .await?;
+
+            use tl::enums::updates::ChannelDifference::*;
+            match response {
+                Empty(empty) => {
+                    self.update_state.channel_pts.insert(channel_id, empty.pts);
+                    break;
+                }
+                Difference(diff) => {
+                    self.update_state.remember_channels(&diff.chats);
+                    difference.extend_channel_messages(diff.new_messages);
+                    difference.updates.extend(diff.other_updates);
+                    difference.users.extend(diff.users);
+                    difference.chats.extend(diff.chats);
+                    self.update_state.channel_pts.insert(channel_id, diff.pts);
+                    if diff.final_ {
+                        break;
+                    }
+                }
+                TooLong(too_long) => {
+                    self.update_state.remember_channels(&too_long.chats);
+                    difference.extend_channel_messages(too_long.messages);
+                    difference.users.extend(too_long.users);
+                    difference.chats.extend(too_long.chats);
+                    if let tl::enums::Dialog::Dialog(dialog) = too_long.dialog {
+                        self.update_state
+                            .channel_pts
+                            .insert(channel_id, dialog.pts.unwrap_or(0));
+                    }
+                    break;
+                }
+            }
+        }
+
+        Ok(difference)
+    }
+
+    /// Applies gap detection and recovery to a batch of updates that arrived together (a
+    /// `updates.updates`/`updates.updatesCombined` container, or a synthesized single update).
+    ///
+    /// `seq_start` is checked for contiguity against the local state, while `seq` (the
+    /// container's ending sequence, equal to `seq_start` outside of `updatesCombined`) is what
+    /// gets persisted — collapsing the two would make every container after a `Combined` one
+    /// look like it has a gap. A `seq_start` of `0` means "no sequence to validate", matching the
+    /// short update variants.
+    async fn process_updates(
+        &mut self,
+        seq_start: i32,
+        seq: i32,
+        date: i32,
+        mut users: Vec<tl::enums::User>,
+        mut chats: Vec<tl::enums::Chat>,
+        raw: Vec<tl::enums::Update>,
+    ) -> Result<
+        (
+            Vec<tl::enums::Update>,
+            Vec<tl::enums::User>,
+            Vec<tl::enums::Chat>,
+        ),
+        ReadError,
+    > {
+        self.update_state.remember_channels(&chats);
+
+        if seq_start != 0 {
+            match classify_seq(self.update_state.seq, seq_start) {
+                SeqDecision::Stale => return Ok((Vec::new(), users, chats)),
+                SeqDecision::Gap => {
+                    let difference = self.get_difference().await?;
+                    users.extend(difference.users);
+                    chats.extend(difference.chats);
+                    return Ok((difference.updates, users, chats));
+                }
+                SeqDecision::Apply => {
+                    self.update_state.seq = seq;
+                    self.update_state.date = date;
+                }
+            }
+        }
+
+        let mut result = Vec::with_capacity(raw.len());
+        for update in raw {
+            match pts_scope(&update) {
+                PtsScope::None => result.push(update),
+                PtsScope::Common { pts, pts_count } => {
+                    match classify_pts(self.update_state.pts, pts, pts_count) {
+                        PtsDecision::Apply(pts) => {
+                            self.update_state.pts = pts;
+                            result.push(update);
+                        }
+                        PtsDecision::Drop => {}
+                        PtsDecision::Gap => {
+                            let difference = self.get_difference().await?;
+                            users.extend(difference.users);
+                            chats.extend(difference.chats);
+                            result.extend(difference.updates);
+                        }
+                    }
+                }
+                PtsScope::Channel {
+                    channel_id,
+                    pts,
+                    pts_count,
+                } => {
+                    let local_pts = *self.update_state.channel_pts.get(&channel_id).unwrap_or(&0);
+                    match classify_pts(local_pts, pts, pts_count) {
+                        PtsDecision::Apply(pts) => {
+                            self.update_state.channel_pts.insert(channel_id, pts);
+                            result.push(update);
+                        }
+                        PtsDecision::Drop => {}
+                        PtsDecision::Gap => {
+                            let difference = self.get_channel_difference(channel_id).await?;
+                            users.extend(difference.users);
+                            chats.extend(difference.chats);
+                            result.extend(difference.updates);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok((result, users, chats))
+    }
+
     /// Returns an iterator with the last updates and some of the entities used in them
     /// in a set for easy access.
     ///
@@ -49,6 +467,8 @@ impl Client {
     ) -> Result<(UpdateIter, EntitySet<'b>), ReadError> {
         use tl::enums::Updates::*;
 
+        self.sync_update_state().await?;
+
         loop {
             let mut updates = self.step().await?;
             if updates.len() == 0 {
@@ -57,111 +477,179 @@ impl Client {
                 panic!("telegram returned more than 1 updates in 1 step");
             }
             break match updates.pop().unwrap() {
-                UpdateShort(update) => Ok((UpdateIter::single(update.update), EntitySet::empty())),
-                Combined(update) => Ok((
-                    UpdateIter::multiple(update.updates),
-                    EntitySet::new_owned(update.users, update.chats),
-                )),
-                Updates(update) => Ok((
-                    UpdateIter::multiple(update.updates),
-                    EntitySet::new_owned(update.users, update.chats),
-                )),
+                UpdateShort(update) => {
+                    let (updates, users, chats) = self
+                        .process_updates(
+                            0,
+                            0,
+                            update.date,
+                            Vec::new(),
+                            Vec::new(),
+                            vec![update.update],
+                        )
+                        .await?;
+                    Ok((
+                        UpdateIter::multiple(updates),
+                        EntitySet::new_owned(users, chats),
+                    ))
+                }
+                Combined(update) => {
+                    let (updates, users, chats) = self
+                        .process_updates(
+                            update.seq_start,
+                            update.seq,
+                            update.date,
+                            update.users,
+                            update.chats,
+                            update.updates,
+                        )
+                        .await?;
+                    Ok((
+                        UpdateIter::multiple(updates),
+                        EntitySet::new_owned(users, chats),
+                    ))
+                }
+                Updates(update) => {
+                    let (updates, users, chats) = self
+                        .process_updates(
+                            update.seq,
+                            update.seq,
+                            update.date,
+                            update.users,
+                            update.chats,
+                            update.updates,
+                        )
+                        .await?;
+                    Ok((
+                        UpdateIter::multiple(updates),
+                        EntitySet::new_owned(users, chats),
+                    ))
+                }
                 // We need to know our self identifier by now or this will fail.
                 // These updates will only happen after we logged in so that's fine.
-                UpdateShortMessage(update) => Ok((
-                    (UpdateIter::single(tl::enums::Update::NewMessage(
-                        tl::types::UpdateNewMessage {
-                            message: tl::enums::Message::Message(tl::types::Message {
-                                out: update.out,
-                                mentioned: update.mentioned,
-                                media_unread: update.media_unread,
-                                silent: update.silent,
-                                post: false,
-                                from_scheduled: false,
-                                legacy: false,
-                                edit_hide: false,
-                                id: update.id,
-                                from_id: Some(tl::enums::Peer::User(tl::types::PeerUser {
-                                    user_id: if update.out {
-                                        // This update can only arrive when logged in (user_id is Some).
-                                        self.user_id().unwrap()
-                                    } else {
-                                        update.user_id
-                                    },
-                                })),
-                                peer_id: tl::enums::Peer::User(tl::types::PeerUser {
-                                    user_id: if update.out {
-                                        update.user_id
-                                    } else {
-                                        // This update can only arrive when logged in (user_id is Some).
-                                        self.user_id().unwrap()
-                                    },
-                                }),
-                                fwd_from: update.fwd_from,
-                                via_bot_id: update.via_bot_id,
-                                reply_to: update.reply_to,
-                                date: update.date,
-                                message: update.message,
-                                media: None,
-                                reply_markup: None,
-                                entities: update.entities,
-                                views: None,
-                                forwards: None,
-                                replies: None,
-                                edit_date: None,
-                                post_author: None,
-                                grouped_id: None,
-                                restriction_reason: None,
+                UpdateShortMessage(update) => {
+                    let synthesized = tl::enums::Update::NewMessage(tl::types::UpdateNewMessage {
+                        message: tl::enums::Message::Message(tl::types::Message {
+                            out: update.out,
+                            mentioned: update.mentioned,
+                            media_unread: update.media_unread,
+                            silent: update.silent,
+                            post: false,
+                            from_scheduled: false,
+                            legacy: false,
+                            edit_hide: false,
+                            id: update.id,
+                            from_id: Some(tl::enums::Peer::User(tl::types::PeerUser {
+                                user_id: if update.out {
+                                    // This update can only arrive when logged in (user_id is Some).
+                                    self.user_id().unwrap()
+                                } else {
+                                    update.user_id
+                                },
+                            })),
+                            peer_id: tl::enums::Peer::User(tl::types::PeerUser {
+                                user_id: if update.out {
+                                    update.user_id
+                                } else {
+                                    // This update can only arrive when logged in (user_id is Some).
+                                    self.user_id().unwrap()
+                                },
                             }),
-                            pts: update.pts,
-                            pts_count: update.pts_count,
-                        },
-                    ))),
-                    EntitySet::empty(),
-                )),
-                UpdateShortChatMessage(update) => Ok((
-                    (UpdateIter::single(tl::enums::Update::NewMessage(
-                        tl::types::UpdateNewMessage {
-                            message: tl::enums::Message::Message(tl::types::Message {
-                                out: update.out,
-                                mentioned: update.mentioned,
-                                media_unread: update.media_unread,
-                                silent: update.silent,
-                                post: false,
-                                from_scheduled: false,
-                                legacy: false,
-                                edit_hide: false,
-                                id: update.id,
-                                from_id: Some(tl::enums::Peer::User(tl::types::PeerUser {
-                                    user_id: update.from_id,
-                                })),
-                                peer_id: tl::enums::Peer::Chat(tl::types::PeerChat {
-                                    chat_id: update.chat_id,
-                                }),
-                                fwd_from: update.fwd_from,
-                                via_bot_id: update.via_bot_id,
-                                reply_to: update.reply_to,
-                                date: update.date,
-                                message: update.message,
-                                media: None,
-                                reply_markup: None,
-                                entities: update.entities,
-                                views: None,
-                                forwards: None,
-                                replies: None,
-                                edit_date: None,
-                                post_author: None,
-                                grouped_id: None,
-                                restriction_reason: None,
+                            fwd_from: update.fwd_from,
+                            via_bot_id: update.via_bot_id,
+                            reply_to: update.reply_to,
+                            date: update.date,
+                            message: update.message,
+                            media: None,
+                            reply_markup: None,
+                            entities: update.entities,
+                            views: None,
+                            forwards: None,
+                            replies: None,
+                            edit_date: None,
+                            post_author: None,
+                            grouped_id: None,
+                            restriction_reason: None,
+                        }),
+                        pts: update.pts,
+                        pts_count: update.pts_count,
+                    });
+                    let (updates, users, chats) = self
+                        .process_updates(
+                            0,
+                            0,
+                            update.date,
+                            Vec::new(),
+                            Vec::new(),
+                            vec![synthesized],
+                        )
+                        .await?;
+                    Ok((
+                        UpdateIter::multiple(updates),
+                        EntitySet::new_owned(users, chats),
+                    ))
+                }
+                UpdateShortChatMessage(update) => {
+                    let synthesized = tl::enums::Update::NewMessage(tl::types::UpdateNewMessage {
+                        message: tl::enums::Message::Message(tl::types::Message {
+                            out: update.out,
+                            mentioned: update.mentioned,
+                            media_unread: update.media_unread,
+                            silent: update.silent,
+                            post: false,
+                            from_scheduled: false,
+                            legacy: false,
+                            edit_hide: false,
+                            id: update.id,
+                            from_id: Some(tl::enums::Peer::User(tl::types::PeerUser {
+                                user_id: update.from_id,
+                            })),
+                            peer_id: tl::enums::Peer::Chat(tl::types::PeerChat {
+                                chat_id: update.chat_id,
                             }),
-                            pts: update.pts,
-                            pts_count: update.pts_count,
-                        },
-                    ))),
-                    EntitySet::empty(),
-                )),
-                // These shouldn't really occur unless triggered via a request
-                TooLong => panic!("should not receive updatesTooLong via passive updates"),
+                            fwd_from: update.fwd_from,
+                            via_bot_id: update.via_bot_id,
+                            reply_to: update.reply_to,
+                            date: update.date,
+                            message: update.message,
+                            media: None,
+                            reply_markup: None,
+                            entities: update.entities,
+                            views: None,
+                            forwards: None,
+                            replies: None,
+                            edit_date: None,
+                            post_author: None,
+                            grouped_id: None,
+                            restriction_reason: None,
+                        }),
+                        pts: update.pts,
+                        pts_count: update.pts_count,
+                    });
+                    let (updates, users, chats) = self
+                        .process_updates(
+                            0,
+                            0,
+                            update.date,
+                            Vec::new(),
+                            Vec::new(),
+                            vec![synthesized],
+                        )
+                        .await?;
+                    Ok((
+                        UpdateIter::multiple(updates),
+                        EntitySet::new_owned(users, chats),
+                    ))
+                }
+                // Rather than a hard desync, fall back to the same recovery path used for an
+                // in-container gap: fetch the full difference from our last known-good state.
+                TooLong => {
+                    let difference = self.get_difference().await?;
+                    Ok((
+                        UpdateIter::multiple(difference.updates),
+                        EntitySet::new_owned(difference.users, difference.chats),
+                    ))
+                }
                 UpdateShortSentMessage(_) => {
                     panic!("should not receive updateShortSentMessage via passive updates")
                 }
@@ -169,3 +657,129 @@ impl Client {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message_in(peer: tl::enums::Peer) -> tl::enums::Message {
+        tl::enums::Message::Message(tl::types::Message {
+            out: false,
+            mentioned: false,
+            media_unread: false,
+            silent: false,
+            post: false,
+            from_scheduled: false,
+            legacy: false,
+            edit_hide: false,
+            id: 1,
+            from_id: None,
+            peer_id: peer,
+            fwd_from: None,
+            via_bot_id: None,
+            reply_to: None,
+            date: 0,
+            message: String::new(),
+            media: None,
+            reply_markup: None,
+            entities: None,
+            views: None,
+            forwards: None,
+            replies: None,
+            edit_date: None,
+            post_author: None,
+            grouped_id: None,
+            restriction_reason: None,
+        })
+    }
+
+    #[test]
+    fn classify_pts_contiguous_applies() {
+        assert_eq!(classify_pts(10, 11, 1), PtsDecision::Apply(11));
+    }
+
+    #[test]
+    fn classify_pts_duplicate_drops() {
+        assert_eq!(classify_pts(10, 8, 1), PtsDecision::Drop);
+    }
+
+    #[test]
+    fn classify_pts_hole_is_a_gap() {
+        assert_eq!(classify_pts(10, 20, 1), PtsDecision::Gap);
+    }
+
+    #[test]
+    fn classify_seq_stale_is_dropped() {
+        assert_eq!(classify_seq(5, 5), SeqDecision::Stale);
+        assert_eq!(classify_seq(5, 4), SeqDecision::Stale);
+    }
+
+    #[test]
+    fn classify_seq_contiguous_applies() {
+        assert_eq!(classify_seq(5, 6), SeqDecision::Apply);
+    }
+
+    #[test]
+    fn classify_seq_hole_is_a_gap() {
+        assert_eq!(classify_seq(5, 7), SeqDecision::Gap);
+    }
+
+    #[test]
+    fn pts_scope_common_for_user_message() {
+        let update = tl::enums::Update::NewMessage(tl::types::UpdateNewMessage {
+            message: message_in(tl::enums::Peer::User(tl::types::PeerUser { user_id: 42 })),
+            pts: 5,
+            pts_count: 1,
+        });
+        match pts_scope(&update) {
+            PtsScope::Common { pts, pts_count } => {
+                assert_eq!(pts, 5);
+                assert_eq!(pts_count, 1);
+            }
+            _ => panic!("expected a common-scoped pts update"),
+        }
+    }
+
+    #[test]
+    fn pts_scope_channel_for_channel_message() {
+        let update = tl::enums::Update::NewChannelMessage(tl::types::UpdateNewChannelMessage {
+            message: message_in(tl::enums::Peer::Channel(tl::types::PeerChannel {
+                channel_id: 7,
+            })),
+            pts: 5,
+            pts_count: 1,
+        });
+        match pts_scope(&update) {
+            PtsScope::Channel {
+                channel_id,
+                pts,
+                pts_count,
+            } => {
+                assert_eq!(channel_id, 7);
+                assert_eq!(pts, 5);
+                assert_eq!(pts_count, 1);
+            }
+            _ => panic!("expected a channel-scoped pts update"),
+        }
+    }
+
+    #[test]
+    fn pts_scope_none_for_unrelated_update() {
+        let update =
+            tl::enums::Update::DeleteChannelMessages(tl::types::UpdateDeleteChannelMessages {
+                channel_id: 7,
+                messages: vec![1, 2],
+                pts: 9,
+                pts_count: 2,
+            });
+        // This one does carry a pts, just via the channel-scoped arm.
+        assert!(matches!(pts_scope(&update), PtsScope::Channel { .. }));
+
+        // An update kind `pts_scope` doesn't special-case is exempt from gap detection.
+        let unrelated_update = tl::enums::Update::UserTyping(tl::types::UpdateUserTyping {
+            user_id: 42,
+            action: tl::enums::SendMessageAction::TypingAction,
+        });
+        assert!(matches!(pts_scope(&unrelated_update), PtsScope::None));
+    }
+}